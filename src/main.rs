@@ -1,27 +1,57 @@
+use atom_syndication::{CategoryBuilder, Entry, EntryBuilder, Feed, FeedBuilder, LinkBuilder};
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use simple_logger::SimpleLogger;
 
 use std::{
+    collections::{HashMap, HashSet},
     env,
     error::Error,
     fs::{self, DirEntry, ReadDir},
     iter,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+const ROOT_TEMPLATE: &str = include_str!("templates/root.html");
+const DIRECTORY_TEMPLATE: &str = include_str!("templates/directory_list.html");
+const MANIFEST_PATH: &str = ".build-cache.json";
+
+fn content_hash(chunks: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for chunk in chunks {
+        hasher.update(chunk);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
 fn wrap_directory(name: &str, content: &str) -> String {
-    include_str!("templates/directory_list.html")
+    DIRECTORY_TEMPLATE
         .replace("{{name}}", name)
         .replace("{{content}}", content)
 }
 
-fn wrap_root(ancestors: &[Ancestor], content: &str, name: &str) -> String {
-    include_str!("templates/root.html")
+fn wrap_root(
+    ancestors: &[Ancestor],
+    content: &str,
+    file_name: &str,
+    title: &str,
+    navigation: &str,
+) -> String {
+    ROOT_TEMPLATE
         .replace("{{content}}", content)
-        .replace("{{breadcrumbs}}", &breadcrumbs_html(ancestors, name))
+        .replace("{{title}}", title)
+        .replace("{{navigation}}", navigation)
+        .replace(
+            "{{breadcrumbs}}",
+            &breadcrumbs_html(ancestors, file_name, title),
+        )
 }
 
-fn breadcrumbs_html(ancestors: &[Ancestor], file_name: &str) -> String {
+fn breadcrumbs_html(ancestors: &[Ancestor], file_name: &str, title: &str) -> String {
     let mut previous_path = String::new();
     let mut ancestors = ancestors.iter();
     let mut result = Vec::new();
@@ -39,7 +69,7 @@ fn breadcrumbs_html(ancestors: &[Ancestor], file_name: &str) -> String {
                 result.push(format!(r#"<span>{name}</span>"#));
             } else {
                 result.push(format!(r#"<a href="{previous_path}">{name}</a>"#));
-                result.push(format!(r#"<span>{file_name}</span>"#));
+                result.push(format!(r#"<span>{title}</span>"#));
             }
         } else {
             result.push(format!(r#"<a href="{previous_path}">{name}</a>"#));
@@ -53,6 +83,76 @@ fn breadcrumbs_html(ancestors: &[Ancestor], file_name: &str) -> String {
     }
 }
 
+#[derive(Debug, Clone, Default, Deserialize)]
+struct FrontMatter {
+    title: Option<String>,
+    date: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug)]
+struct MarkdownFile {
+    content: String,
+    front_matter: FrontMatter,
+}
+
+fn parse_front_matter(content: &str) -> MarkdownFile {
+    let Some(rest) = content.strip_prefix("---\n") else {
+        return MarkdownFile {
+            content: content.to_string(),
+            front_matter: FrontMatter::default(),
+        };
+    };
+
+    let Some(end) = rest.find("\n---") else {
+        return MarkdownFile {
+            content: content.to_string(),
+            front_matter: FrontMatter::default(),
+        };
+    };
+
+    let (yaml, rest) = rest.split_at(end);
+    let body = rest
+        .strip_prefix("\n---")
+        .unwrap_or(rest)
+        .trim_start_matches('\n');
+
+    let front_matter = serde_yaml::from_str(yaml).unwrap_or_else(|err| {
+        log::warn!("unable to parse front-matter: {err}");
+        FrontMatter::default()
+    });
+
+    MarkdownFile {
+        content: body.to_string(),
+        front_matter,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    hash: String,
+    children: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Manifest {
+    entries: HashMap<String, ManifestEntry>,
+}
+
+fn incremental_enabled() -> bool {
+    env::var("INCREMENTAL").is_ok()
+}
+
+fn load_manifest() -> Manifest {
+    fs::read_to_string(MANIFEST_PATH)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
 #[derive(Debug)]
 enum NodeContent<FileContent, DirectoryContent> {
     Directory(DirectoryContent),
@@ -61,15 +161,22 @@ enum NodeContent<FileContent, DirectoryContent> {
 
 #[derive(Debug)]
 struct MarkdownNode {
-    content: NodeContent<String, (Option<String>, Vec<MarkdownNode>)>,
+    content: NodeContent<MarkdownFile, (Option<MarkdownFile>, Vec<MarkdownNode>)>,
     file_name: String,
     ancestors: Vec<Ancestor>,
+    modified: SystemTime,
+    hash: String,
+    source_dir: PathBuf,
 }
 
 #[derive(Debug)]
 struct HtmlNode {
     path: PathBuf,
     content: NodeContent<String, (String, Vec<HtmlNode>)>,
+    draft: bool,
+    manifest_key: String,
+    hash: String,
+    children_keys: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -83,6 +190,7 @@ struct FileNode {
     path: PathBuf,
     content: NodeContent<String, ReadDir>,
     ancestors: Vec<Ancestor>,
+    modified: SystemTime,
 }
 
 impl TryFrom<(Vec<Ancestor>, DirEntry)> for FileNode {
@@ -111,6 +219,10 @@ impl TryFrom<DirEntry> for FileNode {
             .metadata()
             .map_err(|err| format!("unable to read metadata for '{file_name}': {err}"))?;
 
+        let modified = metadata
+            .modified()
+            .map_err(|err| format!("unable to read modification time for '{file_name}': {err}"))?;
+
         let content = if metadata.is_dir() {
             NodeContent::Directory(
                 fs::read_dir(path)
@@ -127,6 +239,7 @@ impl TryFrom<DirEntry> for FileNode {
             path: value.path(),
             content,
             ancestors: Vec::new(),
+            modified,
         })
     }
 }
@@ -140,15 +253,33 @@ impl TryFrom<FileNode> for MarkdownNode {
             content,
             file_name,
             ancestors,
+            modified,
         } = value;
 
         match content {
             NodeContent::File(content) => {
                 log::info!(r#"  parsing file: "{file_name}""#);
+                let source_dir = path
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_else(|| PathBuf::from("."));
+                let include_bytes = extract_include_paths(&content)
+                    .into_iter()
+                    .filter_map(|include_path| fs::read_to_string(source_dir.join(include_path)).ok())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let hash = content_hash(&[
+                    content.as_bytes(),
+                    ROOT_TEMPLATE.as_bytes(),
+                    include_bytes.as_bytes(),
+                ]);
                 Ok(MarkdownNode {
-                    content: NodeContent::File(content),
+                    content: NodeContent::File(parse_front_matter(&content)),
                     ancestors,
                     file_name,
+                    modified,
+                    hash,
+                    source_dir,
                 })
             }
             NodeContent::Directory(entries) => {
@@ -167,23 +298,49 @@ impl TryFrom<FileNode> for MarkdownNode {
                 ])
                 .concat();
 
-                let children = entries
+                let entries = entries
                     .map(|entry| {
                         entry.map_err(|err| {
                             format!("unable to get child in directory {file_name}: {err}")
                         })
                     })
-                    .map(|entry| entry.map(|v| (file_ancestors.clone(), v)))
-                    .map(|entry| entry.map(FileNode::try_from)?.map(Self::try_from)?)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let entries = entries
+                    .into_iter()
+                    .filter(|entry| entry.file_name() != "README.md")
+                    .collect::<Vec<_>>();
+
+                let children = entries
+                    .into_par_iter()
+                    .map(|entry| FileNode::try_from((file_ancestors.clone(), entry)))
+                    .map(|entry| entry.and_then(Self::try_from))
                     .collect::<Result<Vec<_>, _>>()?;
 
+                let readme_raw = fs::read_to_string(readme_path).ok();
+                let hash = match &readme_raw {
+                    Some(readme) => {
+                        let include_bytes = extract_include_paths(readme)
+                            .into_iter()
+                            .filter_map(|include_path| fs::read_to_string(path.join(include_path)).ok())
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        content_hash(&[
+                            readme.as_bytes(),
+                            ROOT_TEMPLATE.as_bytes(),
+                            include_bytes.as_bytes(),
+                        ])
+                    }
+                    None => content_hash(&[ROOT_TEMPLATE.as_bytes(), DIRECTORY_TEMPLATE.as_bytes()]),
+                };
+                let readme = readme_raw.as_deref().map(parse_front_matter);
+
                 Ok(MarkdownNode {
-                    content: NodeContent::Directory((
-                        fs::read_to_string(readme_path).ok(),
-                        children,
-                    )),
+                    content: NodeContent::Directory((readme, children)),
                     ancestors,
                     file_name,
+                    modified,
+                    hash,
+                    source_dir: path,
                 })
             }
         }
@@ -233,52 +390,517 @@ fn file_path(ancestors: &[Ancestor], name: &str) -> PathBuf {
     PathBuf::from(output_dir()).join(file_name(ancestors, name))
 }
 
-impl From<MarkdownNode> for HtmlNode {
-    fn from(node: MarkdownNode) -> Self {
-        let content = match node.content {
-            NodeContent::File(content) => NodeContent::File(wrap_root(
+struct NavNode {
+    title: String,
+    href: PathBuf,
+    target: PathBuf,
+    children: Vec<NavNode>,
+}
+
+fn build_nav_tree(node: &MarkdownNode) -> Option<NavNode> {
+    match &node.content {
+        NodeContent::File(file) => {
+            if file.front_matter.draft && !include_drafts() {
+                return None;
+            }
+            Some(NavNode {
+                title: file
+                    .front_matter
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| node.file_name.clone()),
+                href: file_name(&node.ancestors, &node.file_name),
+                target: file_path(&node.ancestors, &node.file_name),
+                children: Vec::new(),
+            })
+        }
+        NodeContent::Directory((_, children)) => Some(NavNode {
+            title: node.file_name.clone(),
+            href: file_name(&node.ancestors, &node.file_name),
+            target: file_path(&node.ancestors, &node.file_name),
+            children: children
+                .iter()
+                .filter(|child| child.file_name != "README.md")
+                .sorted_by(|a, b| a.file_name.cmp(&b.file_name))
+                .filter_map(build_nav_tree)
+                .collect(),
+        }),
+    }
+}
+
+fn render_nav_node(node: &NavNode, current: &Path) -> String {
+    let active = if node.target == current {
+        r#" aria-current="page" class="active""#
+    } else {
+        ""
+    };
+    let href = node.href.to_str().unwrap();
+
+    if node.children.is_empty() {
+        format!(r#"<li><a href="/{href}"{active}>{}</a></li>"#, node.title)
+    } else {
+        let children = node
+            .children
+            .iter()
+            .map(|child| render_nav_node(child, current))
+            .fold(String::new(), |acc, curr| acc + &curr);
+        format!(
+            r#"<li class="directory-listing"><a href="/{href}"{active}>{}</a><ul>{children}</ul></li>"#,
+            node.title
+        )
+    }
+}
+
+fn navigation_html(tree: &NavNode, current: &Path) -> String {
+    let content = tree
+        .children
+        .iter()
+        .map(|child| render_nav_node(child, current))
+        .fold(String::new(), |acc, curr| acc + &curr);
+    format!("<ul>{content}</ul>")
+}
+
+struct NodeContext<'a> {
+    ancestors: &'a [Ancestor],
+    file_name: &'a str,
+    source_dir: &'a Path,
+}
+
+trait Preprocessor {
+    fn run(&self, content: &str, context: &NodeContext) -> Result<String, String>;
+}
+
+struct IncludePreprocessor;
+
+fn extract_include_paths(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix("{{#include ")
+                .and_then(|rest| rest.strip_suffix("}}"))
+                .map(str::trim)
+        })
+        .collect()
+}
+
+impl Preprocessor for IncludePreprocessor {
+    fn run(&self, content: &str, context: &NodeContext) -> Result<String, String> {
+        content
+            .lines()
+            .map(|line| {
+                let Some(include_path) = line
+                    .trim()
+                    .strip_prefix("{{#include ")
+                    .and_then(|rest| rest.strip_suffix("}}"))
+                    .map(str::trim)
+                else {
+                    return Ok(line.to_string());
+                };
+                fs::read_to_string(context.source_dir.join(include_path)).map_err(|err| {
+                    format!(
+                        "unable to include '{include_path}' in '{}': {err}",
+                        context.file_name
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|lines| lines.join("\n"))
+    }
+}
+
+struct TableOfContentsPreprocessor;
+
+impl Preprocessor for TableOfContentsPreprocessor {
+    fn run(&self, content: &str, _context: &NodeContext) -> Result<String, String> {
+        if !content.contains("{{#toc}}") {
+            return Ok(content.to_string());
+        }
+
+        let toc = content
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .map(|line| {
+                let level = line.chars().take_while(|char| *char == '#').count();
+                let title = line.trim_start_matches('#').trim();
+                let indent = "  ".repeat(level.saturating_sub(1));
+                format!("{indent}- {title}")
+            })
+            .join("\n");
+
+        Ok(content.replace("{{#toc}}", &toc))
+    }
+}
+
+struct ExternalPreprocessor {
+    command: String,
+}
+
+impl Preprocessor for ExternalPreprocessor {
+    fn run(&self, content: &str, _context: &NodeContext) -> Result<String, String> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("unable to spawn preprocessor '{}': {err}", self.command))?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| format!("unable to open stdin for preprocessor '{}'", self.command))?;
+        let content = content.to_string();
+        let command = self.command.clone();
+        let writer = std::thread::spawn(move || {
+            stdin
+                .write_all(content.as_bytes())
+                .map_err(|err| format!("unable to write to preprocessor '{command}': {err}"))
+        });
+
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("preprocessor '{}' failed: {err}", self.command))?;
+
+        writer
+            .join()
+            .map_err(|_| format!("preprocessor '{}' writer thread panicked", self.command))??;
+
+        String::from_utf8(output.stdout).map_err(|err| {
+            format!(
+                "preprocessor '{}' produced invalid UTF-8: {err}",
+                self.command
+            )
+        })
+    }
+}
+
+fn external_preprocessors() -> Vec<ExternalPreprocessor> {
+    env::var("PREPROCESSORS")
+        .ok()
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|command| !command.is_empty())
+                .map(|command| ExternalPreprocessor {
+                    command: command.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn preprocessors() -> Vec<Box<dyn Preprocessor>> {
+    let mut preprocessors: Vec<Box<dyn Preprocessor>> = vec![
+        Box::new(IncludePreprocessor),
+        Box::new(TableOfContentsPreprocessor),
+    ];
+    preprocessors.extend(
+        external_preprocessors()
+            .into_iter()
+            .map(|preprocessor| Box::new(preprocessor) as Box<dyn Preprocessor>),
+    );
+    preprocessors
+}
+
+fn run_preprocessors(content: &str, context: &NodeContext) -> Result<String, String> {
+    preprocessors()
+        .iter()
+        .try_fold(content.to_string(), |content, preprocessor| {
+            preprocessor.run(&content, context)
+        })
+}
+
+fn nav_tree_hash(nav: &NavNode) -> String {
+    let mut chunks = Vec::new();
+
+    fn collect(nav: &NavNode, chunks: &mut Vec<String>) {
+        chunks.push(format!("{}|{}", nav.title, nav.href.to_string_lossy()));
+        for child in &nav.children {
+            collect(child, chunks);
+        }
+    }
+
+    collect(nav, &mut chunks);
+    content_hash(&[chunks.join("\n").as_bytes()])
+}
+
+impl TryFrom<MarkdownNode> for HtmlNode {
+    type Error = String;
+
+    fn try_from(node: MarkdownNode) -> Result<Self, Self::Error> {
+        let nav = build_nav_tree(&node).unwrap_or_else(|| NavNode {
+            title: node.file_name.clone(),
+            href: PathBuf::new(),
+            target: PathBuf::new(),
+            children: Vec::new(),
+        });
+        let nav_hash = nav_tree_hash(&nav);
+        markdown_node_to_html_node(node, &nav, &nav_hash)
+    }
+}
+
+fn markdown_node_to_html_node(
+    node: MarkdownNode,
+    nav: &NavNode,
+    nav_hash: &str,
+) -> Result<HtmlNode, String> {
+    let draft = match &node.content {
+        NodeContent::File(file) => file.front_matter.draft,
+        NodeContent::Directory((readme, _)) => {
+            readme.as_ref().is_some_and(|readme| readme.front_matter.draft)
+        }
+    };
+
+    let children_keys = match &node.content {
+        NodeContent::File(_) => Vec::new(),
+        NodeContent::Directory((_, children)) => children
+            .iter()
+            .map(|child| {
+                file_name(&child.ancestors, &child.file_name)
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .sorted()
+            .collect(),
+    };
+
+    let navigation = navigation_html(nav, &file_path(&node.ancestors, &node.file_name));
+
+    let content = match node.content {
+        NodeContent::File(file) => {
+            let context = NodeContext {
+                ancestors: &node.ancestors,
+                file_name: &node.file_name,
+                source_dir: &node.source_dir,
+            };
+            let preprocessed = run_preprocessors(&file.content, &context)?;
+            NodeContent::File(wrap_root(
                 &node.ancestors,
-                &markdown::to_html(&content),
+                &markdown::to_html(&preprocessed),
                 &node.file_name,
-            )),
-            NodeContent::Directory((content, children)) => NodeContent::Directory((
-                wrap_root(
-                    &node.ancestors,
-                    &content.as_ref().map_or_else(
-                        || wrap_directory(&node.file_name, &directory_list_html(&children)),
-                        |content| markdown::to_html(content),
-                    ),
-                    &node.file_name,
-                ),
-                children.into_iter().map(Self::from).collect(),
-            )),
+                file.front_matter.title.as_deref().unwrap_or(&node.file_name),
+                &navigation,
+            ))
+        }
+        NodeContent::Directory((readme, children)) => {
+            let rendered = match &readme {
+                Some(readme) => {
+                    let context = NodeContext {
+                        ancestors: &node.ancestors,
+                        file_name: &node.file_name,
+                        source_dir: &node.source_dir,
+                    };
+                    let preprocessed = run_preprocessors(&readme.content, &context)?;
+                    markdown::to_html(&preprocessed)
+                }
+                None => wrap_directory(&node.file_name, &directory_list_html(&children)),
+            };
+            let title = readme
+                .as_ref()
+                .and_then(|readme| readme.front_matter.title.as_deref())
+                .unwrap_or(&node.file_name);
+            NodeContent::Directory((
+                wrap_root(&node.ancestors, &rendered, &node.file_name, title, &navigation),
+                children
+                    .into_par_iter()
+                    .map(|child| markdown_node_to_html_node(child, nav, nav_hash))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ))
+        }
+    };
+
+    Ok(HtmlNode {
+        manifest_key: file_name(&node.ancestors, &node.file_name)
+            .to_string_lossy()
+            .to_string(),
+        path: file_path(&node.ancestors, &node.file_name),
+        content,
+        draft,
+        hash: content_hash(&[node.hash.as_bytes(), nav_hash.as_bytes()]),
+        children_keys,
+    })
+}
+
+fn include_drafts() -> bool {
+    env::var("INCLUDE_DRAFTS").is_ok()
+}
+
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            distances[i][j] = if a[i - 1] == b[j - 1] {
+                distances[i - 1][j - 1]
+            } else {
+                1 + distances[i - 1][j]
+                    .min(distances[i][j - 1])
+                    .min(distances[i - 1][j - 1])
+            };
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+fn extract_local_hrefs(html: &str) -> Vec<String> {
+    let mut hrefs = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(r#"href=""#) {
+        rest = &rest[start + r#"href=""#.len()..];
+        let Some(end) = rest.find('"') else {
+            break;
         };
+        let href = &rest[..end];
+        if href.starts_with('/') {
+            hrefs.push(href.to_string());
+        }
+        rest = &rest[end..];
+    }
+    hrefs
+}
 
-        HtmlNode {
-            path: file_path(&node.ancestors, &node.file_name),
-            content,
+fn collect_routes(node: &HtmlNode, routes: &mut HashSet<String>) {
+    if node.draft && !include_drafts() {
+        return;
+    }
+    routes.insert(format!("/{}", node.manifest_key));
+    if let NodeContent::Directory((_, children)) = &node.content {
+        for child in children {
+            collect_routes(child, routes);
+        }
+    }
+}
+
+fn strip_fragment_and_query(href: &str) -> &str {
+    href.split(['#', '?']).next().unwrap_or(href)
+}
+
+fn check_html_links(html: &str, routes: &HashSet<String>, broken: &mut Vec<String>) {
+    for href in extract_local_hrefs(html) {
+        let route_path = strip_fragment_and_query(&href);
+        if routes.contains(route_path) {
+            continue;
+        }
+
+        let suggestion = routes
+            .iter()
+            .map(|route| (route, levenshtein_distance(route_path, route)))
+            .min_by_key(|(_, distance)| *distance)
+            .filter(|(_, distance)| *distance <= route_path.len() / 3 + 1)
+            .map(|(route, _)| route.clone());
+
+        broken.push(match suggestion {
+            Some(route) => format!(r#"broken link "{href}", did you mean "{route}"?"#),
+            None => format!(r#"broken link "{href}""#),
+        });
+    }
+}
+
+fn check_node_links(node: &HtmlNode, routes: &HashSet<String>, broken: &mut Vec<String>) {
+    match &node.content {
+        NodeContent::File(content) => check_html_links(content, routes, broken),
+        NodeContent::Directory((content, children)) => {
+            check_html_links(content, routes, broken);
+            for child in children {
+                check_node_links(child, routes, broken);
+            }
         }
     }
 }
 
-fn write_node_to_dir(node: HtmlNode) -> Result<(), Box<dyn Error>> {
+fn downgrade_broken_links() -> bool {
+    env::var("IGNORE_BROKEN_LINKS").is_ok()
+}
+
+fn check_links(root: &HtmlNode) -> Result<(), String> {
+    let mut routes = HashSet::new();
+    collect_routes(root, &mut routes);
+
+    let mut broken = Vec::new();
+    check_node_links(root, &routes, &mut broken);
+
+    if broken.is_empty() {
+        return Ok(());
+    }
+
+    let message = broken.join("\n");
+    if downgrade_broken_links() {
+        log::warn!("{message}");
+        Ok(())
+    } else {
+        Err(message)
+    }
+}
+
+fn write_node_to_dir(
+    node: HtmlNode,
+    old_manifest: &Manifest,
+) -> Result<Manifest, Box<dyn Error + Send + Sync>> {
+    if node.draft && !include_drafts() {
+        log::info!("  skipping draft {:?}", node.path);
+        return Ok(Manifest::default());
+    }
+
+    let unchanged = old_manifest
+        .entries
+        .get(&node.manifest_key)
+        .is_some_and(|entry| entry.hash == node.hash && entry.children == node.children_keys);
+
+    let mut manifest = Manifest::default();
+    manifest.entries.insert(
+        node.manifest_key.clone(),
+        ManifestEntry {
+            hash: node.hash.clone(),
+            children: node.children_keys.clone(),
+        },
+    );
+
     match node.content {
         NodeContent::File(content) => {
-            log::info!("  writing to {:?}", node.path);
-            fs::write(node.path, content)?;
+            if unchanged && node.path.exists() {
+                log::info!("  skipping unchanged {:?}", node.path);
+            } else {
+                log::info!("  writing to {:?}", node.path);
+                fs::write(node.path, content)?;
+            }
         }
         NodeContent::Directory((content, children)) => {
-            let file_path = &node.path.join("index.html");
-            fs::create_dir(&node.path)?;
-            fs::write(file_path, content)?;
-            log::info!("  writing to {:?}", file_path);
-            for node in children {
-                write_node_to_dir(node)?;
+            let index_path = node.path.join("index.html");
+            if !node.path.exists() {
+                fs::create_dir(&node.path)?;
+            }
+            if unchanged && index_path.exists() {
+                log::info!("  skipping unchanged {index_path:?}");
+            } else {
+                log::info!("  writing to {index_path:?}");
+                fs::write(&index_path, content)?;
+            }
+
+            let child_manifests = children
+                .into_par_iter()
+                .map(|child| write_node_to_dir(child, old_manifest))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for child_manifest in child_manifests {
+                manifest.entries.extend(child_manifest.entries);
             }
         }
     }
 
-    Ok(())
+    Ok(manifest)
 }
 
 fn copy_dir_entry<P: AsRef<Path> + Into<PathBuf> + Clone>(
@@ -291,7 +913,11 @@ fn copy_dir_entry<P: AsRef<Path> + Into<PathBuf> + Clone>(
     let to = to.join(file_name);
 
     if metadata.is_dir() {
-        fs::create_dir(&to)?;
+        if let Err(err) = fs::create_dir(&to) {
+            if err.kind() != std::io::ErrorKind::AlreadyExists {
+                return Err(err.into());
+            }
+        }
         fs::read_dir(entry.path())?
             .map(|entry| copy_dir_entry(&entry?, to.clone()))
             .collect::<Result<Vec<_>, _>>()?;
@@ -302,6 +928,85 @@ fn copy_dir_entry<P: AsRef<Path> + Into<PathBuf> + Clone>(
     Ok(())
 }
 
+fn feed_url() -> Option<String> {
+    env::var("FEED_URL").ok()
+}
+
+fn first_heading(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find(|line| line.starts_with("# "))
+        .map(|line| line.trim_start_matches("# ").trim().to_string())
+}
+
+fn feed_entries(node: &MarkdownNode, base_url: &str, entries: &mut Vec<Entry>) {
+    match &node.content {
+        NodeContent::File(file) => {
+            if file.front_matter.draft && !include_drafts() {
+                return;
+            }
+
+            let title = file
+                .front_matter
+                .title
+                .clone()
+                .or_else(|| first_heading(&file.content))
+                .unwrap_or_else(|| node.file_name.clone());
+            let path = file_name(&node.ancestors, &node.file_name);
+            let updated: DateTime<Utc> = file
+                .front_matter
+                .date
+                .as_deref()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.with_timezone(&Utc))
+                .unwrap_or_else(|| node.modified.into());
+
+            let link = LinkBuilder::default()
+                .href(format!("{base_url}/{}", path.to_str().unwrap()))
+                .build();
+
+            let categories = file
+                .front_matter
+                .tags
+                .iter()
+                .map(|tag| CategoryBuilder::default().term(tag.clone()).build())
+                .collect::<Vec<_>>();
+
+            let entry = EntryBuilder::default()
+                .title(title)
+                .id(format!("{base_url}/{}", path.to_str().unwrap()))
+                .updated(updated.fixed_offset())
+                .links(vec![link])
+                .categories(categories)
+                .build();
+
+            entries.push(entry);
+        }
+        NodeContent::Directory((_, children)) => {
+            for child in children {
+                feed_entries(child, base_url, entries);
+            }
+        }
+    }
+}
+
+fn build_feed(root: &MarkdownNode) -> Option<Feed> {
+    let base_url = feed_url()?;
+
+    let mut entries = Vec::new();
+    feed_entries(root, &base_url, &mut entries);
+    entries.sort_by(|a, b| b.updated().cmp(a.updated()));
+
+    Some(
+        FeedBuilder::default()
+            .title(root_dir_title())
+            .id(base_url)
+            .updated(Utc::now().fixed_offset())
+            .entries(entries)
+            .build(),
+    )
+}
+
 fn root_dir_title() -> String {
     env::var("ROOT_TITLE").unwrap_or_else(|_| String::from("root"))
 }
@@ -314,21 +1019,40 @@ fn main() -> Result<(), Box<dyn Error>> {
     SimpleLogger::new().env().init().unwrap();
     let title = root_dir_title();
     let output_dir = output_dir();
-    log::info!("cleaning {output_dir}/ directory");
-    let _ = fs::remove_dir_all(&output_dir)
-        .map_err(|_| log::info!("  {output_dir}/ directory already empty"));
+
+    let old_manifest = if incremental_enabled() {
+        log::info!("incremental mode enabled, reusing {output_dir}/ where possible");
+        fs::create_dir_all(&output_dir)?;
+        load_manifest()
+    } else {
+        log::info!("cleaning {output_dir}/ directory");
+        let _ = fs::remove_dir_all(&output_dir)
+            .map_err(|_| log::info!("  {output_dir}/ directory already empty"));
+        Manifest::default()
+    };
 
     let root = FileNode {
         file_name: title,
         path: "articles".into(),
         content: NodeContent::Directory(fs::read_dir("articles")?),
         ancestors: Vec::new(),
+        modified: fs::metadata("articles")?.modified()?,
     };
     log::info!("parsing markdown");
     let root: MarkdownNode = root.try_into()?;
+    let feed = build_feed(&root);
     log::info!("compiling to html");
     let root: HtmlNode = root.try_into()?;
-    write_node_to_dir(root)?;
+    log::info!("checking internal links");
+    check_links(&root)?;
+    let new_manifest = write_node_to_dir(root, &old_manifest)?;
+    if incremental_enabled() {
+        fs::write(MANIFEST_PATH, serde_json::to_string_pretty(&new_manifest)?)?;
+    }
+    if let Some(feed) = feed {
+        log::info!("writing feed.xml");
+        fs::write(PathBuf::from(&output_dir).join("feed.xml"), feed.to_string())?;
+    }
     log::info!("copying contents of public/ to {output_dir}/");
     fs::read_dir("public")?
         .map(|entry| copy_dir_entry(&entry?, &output_dir))
@@ -337,3 +1061,114 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levenshtein_distance_identical_strings_is_zero() {
+        assert_eq!(levenshtein_distance("setup.html", "setup.html"), 0);
+    }
+
+    #[test]
+    fn levenshtein_distance_counts_edits() {
+        assert_eq!(levenshtein_distance("setup.html", "setup.htm"), 1);
+        assert_eq!(levenshtein_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn levenshtein_distance_empty_strings() {
+        assert_eq!(levenshtein_distance("", ""), 0);
+        assert_eq!(levenshtein_distance("abc", ""), 3);
+    }
+
+    #[test]
+    fn strip_fragment_and_query_removes_fragment() {
+        assert_eq!(
+            strip_fragment_and_query("/guides/setup.html#install"),
+            "/guides/setup.html"
+        );
+    }
+
+    #[test]
+    fn strip_fragment_and_query_removes_query() {
+        assert_eq!(
+            strip_fragment_and_query("/guides/setup.html?foo=bar"),
+            "/guides/setup.html"
+        );
+    }
+
+    #[test]
+    fn strip_fragment_and_query_leaves_plain_path_untouched() {
+        assert_eq!(
+            strip_fragment_and_query("/guides/setup.html"),
+            "/guides/setup.html"
+        );
+    }
+
+    #[test]
+    fn parse_front_matter_extracts_fields() {
+        let file = parse_front_matter(
+            "---\ntitle: Hello\ndate: 2024-01-01T00:00:00Z\ndraft: true\ntags:\n  - a\n  - b\n---\nbody text",
+        );
+        assert_eq!(file.content, "body text");
+        assert_eq!(file.front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(
+            file.front_matter.date.as_deref(),
+            Some("2024-01-01T00:00:00Z")
+        );
+        assert!(file.front_matter.draft);
+        assert_eq!(file.front_matter.tags, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn parse_front_matter_missing_closing_delimiter_is_treated_as_plain_body() {
+        let file = parse_front_matter("---\ntitle: Hello\nno closing delimiter");
+        assert_eq!(file.content, "---\ntitle: Hello\nno closing delimiter");
+        assert!(file.front_matter.title.is_none());
+    }
+
+    #[test]
+    fn parse_front_matter_malformed_yaml_falls_back_to_defaults() {
+        let file = parse_front_matter("---\ntitle: [unterminated\n---\nbody");
+        assert_eq!(file.content, "body");
+        assert!(file.front_matter.title.is_none());
+        assert!(!file.front_matter.draft);
+    }
+
+    #[test]
+    fn parse_front_matter_without_front_matter_returns_content_unchanged() {
+        let file = parse_front_matter("just a regular article");
+        assert_eq!(file.content, "just a regular article");
+        assert_eq!(file.front_matter.title, None);
+    }
+
+    #[test]
+    fn nav_tree_hash_changes_when_a_title_changes() {
+        let leaf = |title: &str| NavNode {
+            title: title.to_string(),
+            href: PathBuf::from("a.html"),
+            target: PathBuf::from("build/a.html"),
+            children: Vec::new(),
+        };
+        let tree = |title: &str| NavNode {
+            title: "root".to_string(),
+            href: PathBuf::new(),
+            target: PathBuf::new(),
+            children: vec![leaf(title)],
+        };
+        assert_ne!(nav_tree_hash(&tree("A")), nav_tree_hash(&tree("B")));
+    }
+
+    #[test]
+    fn nav_tree_hash_is_stable_for_the_same_tree() {
+        let tree = NavNode {
+            title: "root".to_string(),
+            href: PathBuf::new(),
+            target: PathBuf::new(),
+            children: Vec::new(),
+        };
+        assert_eq!(nav_tree_hash(&tree), nav_tree_hash(&tree));
+    }
+}